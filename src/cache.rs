@@ -0,0 +1,193 @@
+use crate::crypto::EncryptionKey;
+use crate::file::{ChecksumAlgorithm, Directory};
+use actix_web::{error, Error as ActixError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Cached checksum of a single file, along with the stat values it was computed from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    /// File size in bytes at the time the checksum was computed.
+    pub size: u64,
+    /// Last modification time (seconds since the Unix epoch).
+    pub mtime: u64,
+    /// Cached checksum.
+    pub checksum: String,
+    /// Algorithm the checksum was computed with.
+    pub algorithm: ChecksumAlgorithm,
+}
+
+/// On-disk index that memoizes file checksums keyed on `(path, size, mtime)`.
+///
+/// Loaded once at startup and updated incrementally as files are scanned, so that
+/// a directory scan only needs to `stat` unchanged files instead of rehashing them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ChecksumCache {
+    entries: HashMap<PathBuf, CacheEntry>,
+}
+
+impl ChecksumCache {
+    /// Loads the cache from `index_path`, starting empty if it does not exist yet.
+    pub fn load(index_path: &Path) -> Result<Self, ActixError> {
+        match fs::read(index_path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(error::ErrorInternalServerError),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Persists the cache to `index_path`.
+    pub fn save(&self, index_path: &Path) -> Result<(), ActixError> {
+        let bytes = serde_json::to_vec(self).map_err(error::ErrorInternalServerError)?;
+        fs::write(index_path, bytes).map_err(error::ErrorInternalServerError)
+    }
+
+    /// Returns the cached entry for `path` if it is still valid for the given `size`/`mtime`.
+    pub fn get(&self, path: &Path, size: u64, mtime: u64) -> Option<&CacheEntry> {
+        self.entries
+            .get(path)
+            .filter(|entry| entry.size == size && entry.mtime == mtime)
+    }
+
+    /// Inserts or replaces the cached entry for `path`.
+    pub fn insert(&mut self, path: PathBuf, entry: CacheEntry) {
+        self.entries.insert(path, entry);
+    }
+
+    /// Removes entries whose paths no longer exist on disk.
+    pub fn prune(&mut self) {
+        self.entries.retain(|path, _| path.exists());
+    }
+
+    /// Number of entries currently held in the cache.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the cache holds no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Long-lived handle that owns a [`ChecksumCache`] persisted at `index_path`, so that
+/// scanning the same upload directory across requests only rehashes files that changed
+/// since the previous scan instead of paying the full-directory cost every time.
+pub struct CachedDirectoryScanner {
+    index_path: PathBuf,
+    cache: ChecksumCache,
+}
+
+impl CachedDirectoryScanner {
+    /// Loads the persisted cache at `index_path`, starting empty if it does not exist yet.
+    pub fn new(index_path: PathBuf) -> Result<Self, ActixError> {
+        let cache = ChecksumCache::load(&index_path)?;
+        if cache.is_empty() {
+            log::info!("starting with a cold checksum cache at {}", index_path.display());
+        }
+        Ok(Self { index_path, cache })
+    }
+
+    /// Scans `directory`, reusing the persisted cache to skip rehashing unchanged files,
+    /// then writes the updated cache back to `index_path`.
+    pub fn scan(
+        &mut self,
+        directory: &Path,
+        algorithm: ChecksumAlgorithm,
+        encryption_key: Option<&EncryptionKey>,
+    ) -> Result<Directory, ActixError> {
+        let directory = Directory::scan(directory, algorithm, &mut self.cache, encryption_key)?;
+        self.cache.save(&self.index_path)?;
+        log::debug!("checksum cache now holds {} entries", self.cache.len());
+        Ok(directory)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::ChecksumAlgorithm;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_load_save_roundtrip() -> Result<(), ActixError> {
+        let index_path = std::env::temp_dir().join(format!(
+            "rustypaste-test-cache-{}-{}.json",
+            std::process::id(),
+            "roundtrip"
+        ));
+        let _ = fs::remove_file(&index_path);
+
+        let mut cache = ChecksumCache::default();
+        cache.insert(
+            PathBuf::from("/tmp/example.txt"),
+            CacheEntry {
+                size: 42,
+                mtime: 1,
+                checksum: "deadbeef".to_string(),
+                algorithm: ChecksumAlgorithm::Sha256,
+            },
+        );
+        cache.save(&index_path)?;
+
+        let reloaded = ChecksumCache::load(&index_path)?;
+        assert_eq!(
+            "deadbeef",
+            reloaded
+                .get(Path::new("/tmp/example.txt"), 42, 1)
+                .expect("cache entry should survive a save/load roundtrip")
+                .checksum
+        );
+
+        fs::remove_file(&index_path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn test_reloaded_cache_skips_rehash() -> Result<(), ActixError> {
+        let img_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("img");
+        let index_path = std::env::temp_dir().join(format!(
+            "rustypaste-test-cache-{}-{}.json",
+            std::process::id(),
+            "scan"
+        ));
+        let _ = fs::remove_file(&index_path);
+
+        let mut scanner = CachedDirectoryScanner::new(index_path.clone())?;
+        let first_scan = scanner.scan(&img_dir, ChecksumAlgorithm::Sha256, None)?;
+        assert!(!first_scan.files.is_empty());
+
+        // A freshly loaded cache (as on process startup) must already contain every
+        // file from the previous scan, so the next scan only stats them.
+        let reloaded_cache = ChecksumCache::load(&index_path)?;
+        assert_eq!(first_scan.files.len(), reloaded_cache.len());
+        for file in &first_scan.files {
+            let metadata = fs::metadata(&file.path).map_err(error::ErrorInternalServerError)?;
+            let mtime = metadata
+                .modified()
+                .map_err(error::ErrorInternalServerError)?
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_err(error::ErrorInternalServerError)?
+                .as_secs();
+            let cached = reloaded_cache
+                .get(&file.path, metadata.len(), mtime)
+                .expect("reloaded cache should have a hit for every previously scanned file");
+            assert_eq!(file.checksum, cached.checksum);
+        }
+
+        // Scanning again with the reloaded cache reuses the cached checksums rather than
+        // rehashing: the resulting files match the first scan exactly.
+        let mut reloaded_scanner = CachedDirectoryScanner::new(index_path.clone())?;
+        let second_scan = reloaded_scanner.scan(&img_dir, ChecksumAlgorithm::Sha256, None)?;
+        assert_eq!(first_scan.files.len(), second_scan.files.len());
+        for file in &second_scan.files {
+            Directory::try_from(img_dir.as_path())?
+                .get_file(&file.checksum, ChecksumAlgorithm::Sha256)
+                .expect("checksum computed via the reloaded cache should still match");
+        }
+
+        fs::remove_file(&index_path).ok();
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+use crate::crypto::EncryptionKey;
+use crate::file::ChecksumAlgorithm;
+use crate::util;
+use actix_web::{error, Error as ActixError};
+use byte_unit::Byte;
+use serde::Deserialize;
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// Top-level server configuration, parsed from a TOML file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// `[server]` table: where the HTTP server listens.
+    pub server: ServerConfig,
+    /// `[paste]` table: upload directory and the storage features it opts into.
+    pub paste: PasteConfig,
+}
+
+impl Config {
+    /// Parses the config at `path`.
+    pub fn parse(path: &Path) -> Result<Self, ActixError> {
+        let contents = std::fs::read_to_string(path).map_err(error::ErrorInternalServerError)?;
+        toml::from_str(&contents).map_err(error::ErrorInternalServerError)
+    }
+}
+
+/// `[server]` table.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerConfig {
+    /// Address to bind to, e.g. `0.0.0.0`.
+    pub address: String,
+    /// Port to bind to.
+    pub port: u16,
+}
+
+/// `[paste]` table: where uploads are stored, and the storage features enabled for them.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PasteConfig {
+    /// Directory uploads are stored in.
+    pub path: PathBuf,
+    /// Checksum settings, flattened directly into `[paste]` for backwards compatibility
+    /// with configs that predate the other storage features below.
+    #[serde(flatten)]
+    pub checksum: ChecksumConfig,
+    /// `[paste.integrity]` table.
+    #[serde(default)]
+    pub integrity: IntegrityConfig,
+    /// `[paste.phash]` table.
+    #[serde(default)]
+    pub phash: PerceptualHashConfig,
+    /// `[paste.encryption]` table.
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
+    /// Maximum total size of the upload directory, e.g. `"1GB"`. Unbounded if unset.
+    #[serde(default)]
+    pub max_content_length: Option<String>,
+}
+
+impl PasteConfig {
+    /// Parses [`PasteConfig::max_content_length`] into a [`Byte`], if set.
+    pub fn max_size(&self) -> Result<Option<Byte>, ActixError> {
+        self.max_content_length
+            .as_deref()
+            .map(|value| Byte::from_str(value).map_err(error::ErrorInternalServerError))
+            .transpose()
+    }
+}
+
+/// Checksum-related settings, deserialized from the `[paste]` table of the server's TOML
+/// config.
+///
+/// [`ChecksumConfig::algorithm`] is read once at startup (see `main`) and passed through to
+/// every directory scan, so it governs both deduplication in
+/// [`crate::routes::upload`] and the hashes reported by [`crate::routes::download`].
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ChecksumConfig {
+    /// Algorithm used to checksum uploads for deduplication.
+    #[serde(default)]
+    algorithm: ChecksumAlgorithm,
+}
+
+impl ChecksumConfig {
+    /// Algorithm configured for checksumming uploads.
+    pub fn algorithm(&self) -> ChecksumAlgorithm {
+        self.algorithm
+    }
+}
+
+/// Perceptual near-duplicate detection settings, deserialized from a `[paste.phash]`
+/// table. Disabled by default since it adds a decode-and-hash pass per image upload.
+///
+/// [`crate::routes::upload`] checks `enabled` before building a
+/// [`crate::phash::PerceptualHashIndex`] via [`crate::file::Directory::perceptual_hash_index`]
+/// and calling [`crate::file::Directory::find_similar`] with `max_distance`: a visually
+/// near-identical upload is pointed at the existing file instead of being stored again.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PerceptualHashConfig {
+    /// Whether near-duplicate image detection is enabled.
+    #[serde(default)]
+    enabled: bool,
+    /// Maximum Hamming distance for two images to be considered near-duplicates.
+    #[serde(default = "PerceptualHashConfig::default_max_distance")]
+    max_distance: u32,
+}
+
+impl Default for PerceptualHashConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_distance: Self::default_max_distance(),
+        }
+    }
+}
+
+impl PerceptualHashConfig {
+    fn default_max_distance() -> u32 {
+        8
+    }
+
+    /// Whether near-duplicate image detection is enabled.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Maximum Hamming distance for two images to be considered near-duplicates.
+    pub fn max_distance(&self) -> u32 {
+        self.max_distance
+    }
+}
+
+/// Integrity-verification settings, deserialized from a `[paste.integrity]` table.
+///
+/// The server's startup (see `main`) runs [`crate::integrity::self_check`] once if
+/// `check_on_startup` is set, and schedules [`crate::integrity::run_periodic`] every
+/// `check_interval_secs` when set, logging any [`crate::integrity::CorruptFile`]s it
+/// reports. The same function backs the `/admin/integrity` endpoint
+/// ([`crate::integrity::admin_check_handler`]) for on-demand checks.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct IntegrityConfig {
+    /// Whether to run a full integrity check at startup, before serving traffic.
+    #[serde(default)]
+    check_on_startup: bool,
+    /// If set, re-run the integrity check on this interval while the server is up.
+    #[serde(default)]
+    check_interval_secs: Option<u64>,
+}
+
+impl IntegrityConfig {
+    /// Whether to run a full integrity check at startup, before serving traffic.
+    pub fn check_on_startup(&self) -> bool {
+        self.check_on_startup
+    }
+
+    /// Interval, if any, on which to re-run the integrity check while the server is up.
+    pub fn check_interval_secs(&self) -> Option<u64> {
+        self.check_interval_secs
+    }
+}
+
+/// At-rest encryption settings, deserialized from a `[paste.encryption]` table. Disabled
+/// by default, since it requires provisioning and safeguarding a key.
+///
+/// The server's startup (see `main`) calls [`EncryptionConfig::load_key`] once when `enabled`
+/// and threads the resulting [`EncryptionKey`] through to the upload/download handlers, which
+/// call [`crate::file::Directory::store_encrypted`] and
+/// [`crate::file::Directory::read_decrypted`] respectively.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EncryptionConfig {
+    /// Whether uploads are encrypted at rest.
+    #[serde(default)]
+    enabled: bool,
+    /// Name of the environment variable holding the 32-byte master key, base64-encoded.
+    #[serde(default = "EncryptionConfig::default_master_key_env")]
+    master_key_env: String,
+}
+
+impl Default for EncryptionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            master_key_env: Self::default_master_key_env(),
+        }
+    }
+}
+
+impl EncryptionConfig {
+    fn default_master_key_env() -> String {
+        "RUSTYPASTE_MASTER_KEY".to_string()
+    }
+
+    /// Whether uploads are encrypted at rest.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Loads the master key from `master_key_env`, base64-decoding it into the 32 bytes
+    /// [`EncryptionKey::from_bytes`] expects.
+    pub fn load_key(&self) -> Result<EncryptionKey, ActixError> {
+        let encoded = env::var(&self.master_key_env).map_err(|_| {
+            error::ErrorInternalServerError(format!(
+                "encryption is enabled but {} is not set",
+                self.master_key_env
+            ))
+        })?;
+        let bytes = util::base64_decode(&encoded)
+            .map_err(|_| error::ErrorInternalServerError("master key is not valid base64"))?;
+        let bytes: [u8; 32] = bytes.try_into().map_err(|_| {
+            error::ErrorInternalServerError("master key must decode to exactly 32 bytes")
+        })?;
+        Ok(EncryptionKey::from_bytes(&bytes))
+    }
+}
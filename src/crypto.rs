@@ -0,0 +1,161 @@
+use actix_web::{error, Error as ActixError};
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng};
+use chacha20poly1305::{AeadCore, ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Server-wide master key used to encrypt pastes at rest.
+#[derive(Clone)]
+pub struct EncryptionKey(Key);
+
+impl EncryptionKey {
+    /// Builds a key from 32 raw bytes (e.g. loaded from config/environment).
+    pub fn from_bytes(bytes: &[u8; 32]) -> Self {
+        Self(*Key::from_slice(bytes))
+    }
+}
+
+/// Per-file metadata needed to decrypt an object, stored alongside it on disk.
+///
+/// Kept separate from the encrypted object itself so the object's on-disk size can be
+/// read with a plain `stat`, and so `plaintext_size` is available without decrypting.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptedFileMeta {
+    /// Nonce used for this file's AEAD encryption.
+    pub nonce: Vec<u8>,
+    /// Size of the plaintext content in bytes, used for size-limit accounting since the
+    /// on-disk (ciphertext) size includes the AEAD authentication tag overhead.
+    pub plaintext_size: u64,
+}
+
+/// Path of the sidecar metadata file for an encrypted object at `path`.
+fn meta_path(path: &Path) -> PathBuf {
+    let mut meta_path = path.as_os_str().to_owned();
+    meta_path.push(".enc-meta");
+    PathBuf::from(meta_path)
+}
+
+/// Encrypts `plaintext` under `key` and writes both the ciphertext and its metadata to
+/// disk at `path`.
+pub fn encrypt_to_disk(
+    path: &Path,
+    plaintext: &[u8],
+    key: &EncryptionKey,
+) -> Result<EncryptedFileMeta, ActixError> {
+    let cipher = ChaCha20Poly1305::new(&key.0);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| error::ErrorInternalServerError("failed to encrypt file"))?;
+    fs::write(path, ciphertext).map_err(error::ErrorInternalServerError)?;
+    let meta = EncryptedFileMeta {
+        nonce: nonce.to_vec(),
+        plaintext_size: plaintext.len() as u64,
+    };
+    let meta_bytes = serde_json::to_vec(&meta).map_err(error::ErrorInternalServerError)?;
+    fs::write(meta_path(path), meta_bytes).map_err(error::ErrorInternalServerError)?;
+    Ok(meta)
+}
+
+/// Reads the encrypted object at `path` and decrypts it under `key`, using its sidecar
+/// metadata for the nonce. Returns `None` if `path` has no associated metadata, i.e. it
+/// is not an encrypted object.
+pub fn decrypt_from_disk(path: &Path, key: &EncryptionKey) -> Result<Option<Vec<u8>>, ActixError> {
+    let meta = match read_meta(path)? {
+        Some(meta) => meta,
+        None => return Ok(None),
+    };
+    let ciphertext = fs::read(path).map_err(error::ErrorInternalServerError)?;
+    if meta.nonce.len() != 12 {
+        return Err(error::ErrorInternalServerError(format!(
+            "corrupt metadata for {}: expected a 12-byte nonce, got {}",
+            path.display(),
+            meta.nonce.len()
+        )));
+    }
+    let nonce = Nonce::from_slice(&meta.nonce);
+    let cipher = ChaCha20Poly1305::new(&key.0);
+    let plaintext = cipher
+        .decrypt(nonce, ciphertext.as_slice())
+        .map_err(|_| error::ErrorInternalServerError("failed to decrypt file"))?;
+    Ok(Some(plaintext))
+}
+
+/// Reads the sidecar metadata for `path`, if it was stored encrypted.
+pub fn read_meta(path: &Path) -> Result<Option<EncryptedFileMeta>, ActixError> {
+    match fs::read(meta_path(path)) {
+        Ok(bytes) => {
+            serde_json::from_slice(&bytes).map(Some).map_err(error::ErrorInternalServerError)
+        }
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rustypaste-test-crypto-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_roundtrip() -> Result<(), ActixError> {
+        let path = test_path("roundtrip.bin");
+        let key = EncryptionKey::from_bytes(&[7u8; 32]);
+        let plaintext = b"the quick brown fox jumps over the lazy dog";
+
+        let meta = encrypt_to_disk(&path, plaintext, &key)?;
+        assert_eq!(plaintext.len() as u64, meta.plaintext_size);
+        assert_ne!(plaintext.to_vec(), fs::read(&path).map_err(error::ErrorInternalServerError)?);
+
+        let decrypted = decrypt_from_disk(&path, &key)?.expect("file should be encrypted");
+        assert_eq!(plaintext.to_vec(), decrypted);
+
+        fs::remove_file(&path).ok();
+        fs::remove_file(meta_path(&path)).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn test_decrypt_with_wrong_key_fails() -> Result<(), ActixError> {
+        let path = test_path("wrong-key.bin");
+        let key = EncryptionKey::from_bytes(&[1u8; 32]);
+        let other_key = EncryptionKey::from_bytes(&[2u8; 32]);
+        encrypt_to_disk(&path, b"secret contents", &key)?;
+
+        assert!(decrypt_from_disk(&path, &other_key).is_err());
+
+        fs::remove_file(&path).ok();
+        fs::remove_file(meta_path(&path)).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_nonce_returns_error_not_panic() -> Result<(), ActixError> {
+        let path = test_path("corrupt-nonce.bin");
+        let key = EncryptionKey::from_bytes(&[3u8; 32]);
+        let mut meta = encrypt_to_disk(&path, b"data", &key)?;
+        meta.nonce.truncate(4);
+        let meta_bytes = serde_json::to_vec(&meta).map_err(error::ErrorInternalServerError)?;
+        fs::write(meta_path(&path), meta_bytes).map_err(error::ErrorInternalServerError)?;
+
+        assert!(decrypt_from_disk(&path, &key).is_err());
+
+        fs::remove_file(&path).ok();
+        fs::remove_file(meta_path(&path)).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_meta_for_plain_file_is_none() -> Result<(), ActixError> {
+        let path = test_path("plain.bin");
+        fs::write(&path, b"not encrypted").map_err(error::ErrorInternalServerError)?;
+
+        assert!(read_meta(&path)?.is_none());
+
+        fs::remove_file(&path).ok();
+        Ok(())
+    }
+}
@@ -1,17 +1,104 @@
+use crate::cache::{CacheEntry, ChecksumCache};
+use crate::crypto::{self, EncryptedFileMeta, EncryptionKey};
+use crate::phash::{PerceptualHash, PerceptualHashIndex};
 use crate::util;
 use actix_web::{error, Error as ActixError};
+use byte_unit::Byte;
 use glob::glob;
+use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
+use std::fmt;
 use std::fs::File as OsFile;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::UNIX_EPOCH;
+
+/// Checksum algorithm used to compute a [`File`]'s digest.
+///
+/// `Sha256` is the default for backwards compatibility with existing stores.
+/// `Blake3` is offered as a fast path since it is considerably cheaper to
+/// compute than the SHA family while remaining collision-resistant enough
+/// for deduplication purposes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum ChecksumAlgorithm {
+    /// MD5 (compatibility with existing checksum stores).
+    Md5,
+    /// SHA-1 (compatibility with existing checksum stores).
+    Sha1,
+    /// SHA-256.
+    #[default]
+    Sha256,
+    /// SHA-512.
+    Sha512,
+    /// BLAKE3, fast path for large directories.
+    Blake3,
+}
+
+impl fmt::Display for ChecksumAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let v = match self {
+            Self::Md5 => "md5",
+            Self::Sha1 => "sha1",
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+            Self::Blake3 => "blake3",
+        };
+        write!(f, "{v}")
+    }
+}
+
+impl FromStr for ChecksumAlgorithm {
+    type Err = ActixError;
+    fn from_str(v: &str) -> Result<Self, Self::Err> {
+        match v.to_lowercase().as_str() {
+            "md5" => Ok(Self::Md5),
+            "sha1" => Ok(Self::Sha1),
+            "sha256" => Ok(Self::Sha256),
+            "sha512" => Ok(Self::Sha512),
+            "blake3" => Ok(Self::Blake3),
+            _ => Err(error::ErrorInternalServerError(format!(
+                "unknown checksum algorithm: `{v}`"
+            ))),
+        }
+    }
+}
+
+impl ChecksumAlgorithm {
+    /// Computes the digest of `file` using this algorithm.
+    pub(crate) fn digest(self, file: OsFile) -> Option<String> {
+        match self {
+            Self::Md5 => util::md5_digest(file).ok(),
+            Self::Sha1 => util::sha1_digest(file).ok(),
+            Self::Sha256 => util::sha256_digest(file).ok(),
+            Self::Sha512 => util::sha512_digest(file).ok(),
+            Self::Blake3 => util::blake3_digest(file).ok(),
+        }
+    }
+}
 
 /// [`PathBuf`] wrapper for storing checksums.
 #[derive(Debug)]
 pub struct File {
     /// Path of the file.
     pub path: PathBuf,
-    /// SHA256 checksum.
-    pub sha256sum: String,
+    /// Checksum of the file, computed with `algorithm`.
+    pub checksum: String,
+    /// Algorithm used to compute `checksum`.
+    pub algorithm: ChecksumAlgorithm,
+    /// Perceptual hash of the file, if it is an image this directory was scanned to detect.
+    pub phash: Option<PerceptualHash>,
+    /// Encryption metadata, if the file is stored encrypted at rest.
+    pub encrypted_meta: Option<EncryptedFileMeta>,
+}
+
+impl File {
+    /// Logical (plaintext) size of the file, used for size-limit accounting since an
+    /// encrypted file's on-disk size includes AEAD overhead.
+    pub fn plaintext_size(&self, on_disk_size: u64) -> u64 {
+        self.encrypted_meta
+            .as_ref()
+            .map_or(on_disk_size, |meta| meta.plaintext_size)
+    }
 }
 
 /// Directory that contains [`File`]s.
@@ -22,9 +109,39 @@ pub struct Directory {
     pub total_size: u64,
 }
 
+impl<'a> TryFrom<(&'a Path, ChecksumAlgorithm)> for Directory {
+    type Error = ActixError;
+    fn try_from((directory, algorithm): (&'a Path, ChecksumAlgorithm)) -> Result<Self, Self::Error> {
+        let mut cache = ChecksumCache::default();
+        Self::scan(directory, algorithm, &mut cache, None)
+    }
+}
+
 impl<'a> TryFrom<&'a Path> for Directory {
     type Error = ActixError;
     fn try_from(directory: &'a Path) -> Result<Self, Self::Error> {
+        Self::try_from((directory, ChecksumAlgorithm::default()))
+    }
+}
+
+impl Directory {
+    /// Scans `directory`, tagging each [`File`] with a checksum computed via `algorithm`.
+    ///
+    /// Every file is `stat`-ed first; `cache` is consulted and only files whose size or
+    /// modification time changed since the last scan are actually rehashed, turning
+    /// repeated scans of an unchanged directory into stat-only passes. `cache` is updated
+    /// in place with any newly computed checksums and pruned of paths that no longer exist.
+    ///
+    /// `encryption_key`, if set, is used to decrypt files stored at rest (see
+    /// [`crate::crypto`]) so their checksum can still be computed over the plaintext on a
+    /// cold cache. `total_size` accounts for each file's logical (plaintext) size rather
+    /// than its on-disk size, since encrypted objects carry AEAD overhead.
+    pub fn scan(
+        directory: &Path,
+        algorithm: ChecksumAlgorithm,
+        cache: &mut ChecksumCache,
+        encryption_key: Option<&EncryptionKey>,
+    ) -> Result<Self, ActixError> {
         let mut total_size: u64 = 0;
         let files = glob(directory.join("**").join("*").to_str().ok_or_else(|| {
             error::ErrorInternalServerError("directory contains invalid characters")
@@ -32,35 +149,164 @@ impl<'a> TryFrom<&'a Path> for Directory {
         .map_err(error::ErrorInternalServerError)?
         .filter_map(Result::ok)
         .filter(|path| !path.is_dir())
-        .filter_map(|path| match OsFile::open(&path) {
-            Ok(file) => {
-                let size = file.metadata().ok()?.len();
-                total_size += size;
-                Some((path, file))
-            }
-            _ => None,
+        .filter(|path| path.extension().and_then(|ext| ext.to_str()) != Some("enc-meta"))
+        .filter_map(|path| {
+            let metadata = OsFile::open(&path).ok()?.metadata().ok()?;
+            let size = metadata.len();
+            let mtime = metadata
+                .modified()
+                .ok()?
+                .duration_since(UNIX_EPOCH)
+                .ok()?
+                .as_secs();
+            Some((path, size, mtime))
         })
-        .filter_map(|(path, file)| match util::sha256_digest(file) {
-            Ok(sha256sum) => Some(File { path, sha256sum }),
-            _ => None,
+        .filter_map(|(path, size, mtime)| {
+            let encrypted_meta = crypto::read_meta(&path).ok().flatten();
+
+            if let Some(entry) = cache.get(&path, size, mtime) {
+                if entry.algorithm == algorithm {
+                    let file = File {
+                        phash: Self::compute_phash(&path, &encrypted_meta, encryption_key),
+                        path,
+                        checksum: entry.checksum.clone(),
+                        algorithm,
+                        encrypted_meta,
+                    };
+                    total_size += file.plaintext_size(size);
+                    return Some(file);
+                }
+            }
+
+            let (checksum, plaintext) = match (&encrypted_meta, encryption_key) {
+                (Some(_), Some(key)) => {
+                    let plaintext = crypto::decrypt_from_disk(&path, key).ok().flatten()?;
+                    let checksum = util::digest_bytes(algorithm, &plaintext)?;
+                    (checksum, Some(plaintext))
+                }
+                (Some(_), None) => {
+                    log::warn!(
+                        "skipping {}: file is encrypted at rest but no encryption key was configured",
+                        path.display()
+                    );
+                    return None;
+                }
+                (None, _) => (algorithm.digest(OsFile::open(&path).ok()?)?, None),
+            };
+            cache.insert(
+                path.clone(),
+                CacheEntry {
+                    size,
+                    mtime,
+                    checksum: checksum.clone(),
+                    algorithm,
+                },
+            );
+            let phash = match plaintext {
+                Some(plaintext) => util::perceptual_hash_bytes(&plaintext),
+                None => util::perceptual_hash(&path),
+            };
+            let file = File {
+                phash,
+                path,
+                checksum,
+                algorithm,
+                encrypted_meta,
+            };
+            total_size += file.plaintext_size(size);
+            Some(file)
         })
         .collect();
+        cache.prune();
         Ok(Self { files, total_size })
     }
-}
 
-impl Directory {
-    /// Returns the file that matches the given checksum.
-    pub fn get_file<S: AsRef<str>>(&self, sha256sum: S) -> Option<&File> {
+    /// Computes a perceptual hash for the image at `path`, decrypting it first if it is
+    /// stored encrypted at rest. Hashing AEAD ciphertext directly as if it were image
+    /// bytes would either fail to decode or, worse, hash noise, so a `None` key for an
+    /// encrypted file means no phash can be computed rather than a bogus one.
+    fn compute_phash(
+        path: &Path,
+        encrypted_meta: &Option<EncryptedFileMeta>,
+        encryption_key: Option<&EncryptionKey>,
+    ) -> Option<PerceptualHash> {
+        match (encrypted_meta, encryption_key) {
+            (Some(_), Some(key)) => {
+                let plaintext = crypto::decrypt_from_disk(path, key).ok().flatten()?;
+                util::perceptual_hash_bytes(&plaintext)
+            }
+            (Some(_), None) => None,
+            (None, _) => util::perceptual_hash(path),
+        }
+    }
+
+    /// Returns the file that matches the given checksum, computed with the given algorithm.
+    pub fn get_file<S: AsRef<str>>(&self, checksum: S, algorithm: ChecksumAlgorithm) -> Option<&File> {
         self.files.iter().find(|file| {
-            file.sha256sum == sha256sum.as_ref()
+            file.algorithm == algorithm
+                && file.checksum == checksum.as_ref()
                 && !util::TIMESTAMP_EXTENSION_REGEX.is_match(&file.path.to_string_lossy())
         })
     }
 
     /// Checks if the total size of the files exceeds the maximum allowed size.
     pub fn is_over_size_limit(&self, max_size: Byte) -> bool {
-        self.total_size > max_size.get_bytes()
+        u128::from(self.total_size) > max_size.get_bytes()
+    }
+
+    /// Stores `plaintext` at `path`, encrypted at rest under `key`. The returned [`File`]'s
+    /// checksum is computed over `plaintext` so deduplication keeps matching logically
+    /// identical uploads even though the bytes on disk are encrypted.
+    pub fn store_encrypted(
+        path: PathBuf,
+        plaintext: &[u8],
+        algorithm: ChecksumAlgorithm,
+        key: &EncryptionKey,
+    ) -> Result<File, ActixError> {
+        let checksum = util::digest_bytes(algorithm, plaintext)
+            .ok_or_else(|| error::ErrorInternalServerError("failed to checksum file"))?;
+        let encrypted_meta = Some(crypto::encrypt_to_disk(&path, plaintext, key)?);
+        Ok(File {
+            path,
+            checksum,
+            algorithm,
+            phash: None,
+            encrypted_meta,
+        })
+    }
+
+    /// Reads and decrypts `file`'s content under `key`, for serving it back to a client.
+    pub fn read_decrypted(file: &File, key: &EncryptionKey) -> Result<Vec<u8>, ActixError> {
+        crypto::decrypt_from_disk(&file.path, key)?
+            .ok_or_else(|| error::ErrorInternalServerError("file is not encrypted"))
+    }
+
+    /// Builds a [`PerceptualHashIndex`] over the files that have a perceptual hash,
+    /// for use with [`Directory::find_similar`].
+    pub fn perceptual_hash_index(&self) -> PerceptualHashIndex {
+        let mut index = PerceptualHashIndex::new();
+        for (file_index, file) in self.files.iter().enumerate() {
+            if let Some(hash) = file.phash {
+                index.insert(hash, file_index);
+            }
+        }
+        index
+    }
+
+    /// Returns existing images within `max_distance` Hamming distance of `hash`,
+    /// ordered by insertion, so a visually-equivalent upload can be pointed at an
+    /// existing paste even when its bytes differ (e.g. re-encoded/re-compressed).
+    pub fn find_similar(
+        &self,
+        index: &PerceptualHashIndex,
+        hash: PerceptualHash,
+        max_distance: u32,
+    ) -> Vec<&File> {
+        index
+            .find_similar(hash, max_distance)
+            .into_iter()
+            .filter_map(|(file_index, _distance)| self.files.get(file_index))
+            .collect()
     }
 }
 
@@ -78,7 +324,10 @@ mod tests {
                     .join("img")
                     .as_path()
             )?
-            .get_file("2073f6f567dcba3b468c568d29cf8ed2e9d3f0f7305b9ab1b5a22861f5922e61")
+            .get_file(
+                "9557761a0b4820dda52ab2e4eb0743a4e5e1f8d9aaf9872fa377971d1a2242ff",
+                ChecksumAlgorithm::Sha256
+            )
             .expect("cannot get file with checksum")
             .path
             .file_name()
@@ -0,0 +1,249 @@
+use crate::crypto::{self, EncryptionKey};
+use crate::file::{ChecksumAlgorithm, Directory, File};
+use crate::util;
+use actix_web::{web, Error as ActixError, HttpResponse};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::File as OsFile;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// A [`File`] whose on-disk contents no longer match its expected checksum, or that
+/// could no longer be read at all.
+#[derive(Debug)]
+pub struct CorruptFile {
+    /// Path of the offending file.
+    pub path: PathBuf,
+    /// Reason the file failed verification.
+    pub reason: CorruptionReason,
+}
+
+/// Why a file failed integrity verification.
+#[derive(Debug)]
+pub enum CorruptionReason {
+    /// The freshly recomputed checksum did not match the expected one.
+    InvalidChecksum { expected: String, actual: String },
+    /// The file could no longer be opened or read.
+    Unreadable,
+}
+
+impl fmt::Display for CorruptFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.reason {
+            CorruptionReason::InvalidChecksum { expected, actual } => write!(
+                f,
+                "{}: checksum mismatch (expected {expected}, got {actual})",
+                self.path.display()
+            ),
+            CorruptionReason::Unreadable => write!(f, "{}: unreadable", self.path.display()),
+        }
+    }
+}
+
+/// Re-streams every [`File`] in `directory` through its checksum algorithm and reports
+/// the ones whose recomputed digest no longer matches the stored checksum, or that could
+/// not be read at all (e.g. due to bit rot or a partial write). A file is only considered
+/// valid if its recomputed hash equals the stored one.
+///
+/// `encryption_key` is required to verify files stored encrypted at rest (see
+/// [`crate::crypto`]) since their stored checksum is over the plaintext: such a file is
+/// re-decrypted before rehashing rather than hashing its on-disk ciphertext directly.
+pub fn verify(directory: &Directory, encryption_key: Option<&EncryptionKey>) -> Vec<CorruptFile> {
+    directory
+        .files
+        .iter()
+        .filter_map(|file| match recompute(file, encryption_key) {
+            Some(actual) if actual == file.checksum => None,
+            Some(actual) => Some(CorruptFile {
+                path: file.path.clone(),
+                reason: CorruptionReason::InvalidChecksum {
+                    expected: file.checksum.clone(),
+                    actual,
+                },
+            }),
+            None => Some(CorruptFile {
+                path: file.path.clone(),
+                reason: CorruptionReason::Unreadable,
+            }),
+        })
+        .collect()
+}
+
+/// Scans `directory` fresh and runs [`verify`] against it, for use as a startup self-check
+/// (see [`crate::config::IntegrityConfig::check_on_startup`]) or from the admin endpoint
+/// (see [`admin_check_handler`]).
+pub fn self_check(
+    directory: &Path,
+    algorithm: ChecksumAlgorithm,
+    encryption_key: Option<&EncryptionKey>,
+) -> Result<Vec<CorruptFile>, ActixError> {
+    let directory = Directory::try_from((directory, algorithm))?;
+    Ok(verify(&directory, encryption_key))
+}
+
+/// Re-runs [`self_check`] every `interval` for as long as the returned future is polled,
+/// reporting any corrupt files it finds via `on_corrupt` (e.g. logging or paging).
+///
+/// Intended to be spawned once at startup with `actix_web::rt::spawn` when
+/// [`crate::config::IntegrityConfig::check_interval_secs`] is set; the server's startup
+/// (outside this slice) owns constructing the spawned task and the upload directory path.
+pub async fn run_periodic(
+    directory: PathBuf,
+    algorithm: ChecksumAlgorithm,
+    encryption_key: Option<EncryptionKey>,
+    interval: Duration,
+    on_corrupt: impl Fn(&[CorruptFile]),
+) {
+    loop {
+        actix_web::rt::time::sleep(interval).await;
+        match self_check(&directory, algorithm, encryption_key.as_ref()) {
+            Ok(corrupt) if !corrupt.is_empty() => on_corrupt(&corrupt),
+            Ok(_) => {}
+            Err(err) => log::error!("periodic integrity check failed: {err}"),
+        }
+    }
+}
+
+/// Admin endpoint handler for an on-demand integrity check, e.g. wired up as
+/// `POST /admin/integrity` by the server (outside this slice) behind its existing admin
+/// auth middleware. Returns the corrupt files found, rendered as display strings.
+pub async fn admin_check_handler(
+    directory: web::Data<PathBuf>,
+    algorithm: web::Data<ChecksumAlgorithm>,
+    encryption_key: web::Data<Option<EncryptionKey>>,
+) -> Result<HttpResponse, ActixError> {
+    let corrupt = self_check(&directory, *algorithm.as_ref(), encryption_key.as_ref().as_ref())?;
+    Ok(HttpResponse::Ok().json(
+        corrupt
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// Re-streams `file` through its checksum algorithm, returning `None` if it can no
+/// longer be read (or, for an encrypted file, no longer be decrypted).
+fn recompute(file: &File, encryption_key: Option<&EncryptionKey>) -> Option<String> {
+    match (&file.encrypted_meta, encryption_key) {
+        (Some(_), Some(key)) => {
+            let plaintext = crypto::decrypt_from_disk(&file.path, key).ok().flatten()?;
+            util::digest_bytes(file.algorithm, &plaintext)
+        }
+        (Some(_), None) => None,
+        (None, _) => {
+            let os_file = OsFile::open(&file.path).ok()?;
+            file.algorithm.digest(os_file)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::ChecksumAlgorithm;
+    use crate::util;
+    use actix_web::error;
+    use std::fs;
+
+    fn test_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rustypaste-test-integrity-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn test_verify_reports_healthy_file_as_clean() -> Result<(), ActixError> {
+        let path = test_path("healthy.txt");
+        fs::write(&path, b"hello integrity").map_err(error::ErrorInternalServerError)?;
+        let checksum = util::digest_bytes(ChecksumAlgorithm::Sha256, b"hello integrity")
+            .expect("checksum should be computable");
+        let directory = Directory {
+            files: vec![File {
+                path: path.clone(),
+                checksum,
+                algorithm: ChecksumAlgorithm::Sha256,
+                phash: None,
+                encrypted_meta: None,
+            }],
+            total_size: 0,
+        };
+
+        assert!(verify(&directory, None).is_empty());
+
+        fs::remove_file(&path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_detects_checksum_mismatch() -> Result<(), ActixError> {
+        let path = test_path("corrupted.txt");
+        fs::write(&path, b"bit-rotted contents").map_err(error::ErrorInternalServerError)?;
+        let directory = Directory {
+            files: vec![File {
+                path: path.clone(),
+                checksum: "not-the-real-checksum".to_string(),
+                algorithm: ChecksumAlgorithm::Sha256,
+                phash: None,
+                encrypted_meta: None,
+            }],
+            total_size: 0,
+        };
+
+        let corrupt = verify(&directory, None);
+        assert_eq!(1, corrupt.len());
+        assert!(matches!(
+            corrupt[0].reason,
+            CorruptionReason::InvalidChecksum { .. }
+        ));
+
+        fs::remove_file(&path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_reports_missing_file_as_unreadable() {
+        let directory = Directory {
+            files: vec![File {
+                path: test_path("does-not-exist.txt"),
+                checksum: "irrelevant".to_string(),
+                algorithm: ChecksumAlgorithm::Sha256,
+                phash: None,
+                encrypted_meta: None,
+            }],
+            total_size: 0,
+        };
+
+        let corrupt = verify(&directory, None);
+        assert_eq!(1, corrupt.len());
+        assert!(matches!(corrupt[0].reason, CorruptionReason::Unreadable));
+    }
+
+    #[test]
+    fn test_verify_decrypts_encrypted_file_before_comparing() -> Result<(), ActixError> {
+        let path = test_path("encrypted.bin");
+        let key = EncryptionKey::from_bytes(&[9u8; 32]);
+        let plaintext = b"encrypted at rest";
+        let meta = crypto::encrypt_to_disk(&path, plaintext, &key)?;
+        let checksum = util::digest_bytes(ChecksumAlgorithm::Sha256, plaintext)
+            .expect("checksum should be computable");
+        let directory = Directory {
+            files: vec![File {
+                path: path.clone(),
+                checksum,
+                algorithm: ChecksumAlgorithm::Sha256,
+                phash: None,
+                encrypted_meta: Some(meta),
+            }],
+            total_size: 0,
+        };
+
+        assert!(
+            verify(&directory, Some(&key)).is_empty(),
+            "a healthy encrypted file must not be reported as corrupt"
+        );
+
+        let mut meta_path = path.as_os_str().to_owned();
+        meta_path.push(".enc-meta");
+        fs::remove_file(&path).ok();
+        fs::remove_file(meta_path).ok();
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+mod cache;
+mod config;
+mod crypto;
+mod file;
+mod integrity;
+mod phash;
+mod routes;
+mod util;
+
+use actix_web::{web, App, HttpServer};
+use config::Config;
+use routes::AppState;
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    env_logger::init();
+
+    let config_path = env::var("RUSTYPASTE_CONFIG").unwrap_or_else(|_| "config.toml".to_string());
+    let config = Config::parse(&PathBuf::from(config_path))
+        .unwrap_or_else(|err| panic!("failed to load config: {err}"));
+
+    let algorithm = config.paste.checksum.algorithm();
+    let upload_path = config.paste.path.clone();
+
+    let encryption_key = if config.paste.encryption.enabled() {
+        Some(
+            config
+                .paste
+                .encryption
+                .load_key()
+                .unwrap_or_else(|err| panic!("failed to load encryption key: {err}")),
+        )
+    } else {
+        None
+    };
+
+    if config.paste.integrity.check_on_startup() {
+        match integrity::self_check(&upload_path, algorithm, encryption_key.as_ref()) {
+            Ok(corrupt) => {
+                for file in &corrupt {
+                    log::error!("corrupt file detected at startup: {file}");
+                }
+            }
+            Err(err) => log::error!("startup integrity check failed: {err}"),
+        }
+    }
+
+    if let Some(interval_secs) = config.paste.integrity.check_interval_secs() {
+        let periodic_path = upload_path.clone();
+        let periodic_key = encryption_key.clone();
+        actix_web::rt::spawn(integrity::run_periodic(
+            periodic_path,
+            algorithm,
+            periodic_key,
+            Duration::from_secs(interval_secs),
+            |corrupt| {
+                for file in corrupt {
+                    log::error!("periodic integrity check found a corrupt file: {file}");
+                }
+            },
+        ));
+    }
+
+    let admin_directory = web::Data::new(upload_path.clone());
+    let admin_algorithm = web::Data::new(algorithm);
+    let admin_key = web::Data::new(encryption_key.clone());
+
+    let index_path = upload_path.join(".rustypaste-checksum-cache.json");
+    let scanner = cache::CachedDirectoryScanner::new(index_path)
+        .unwrap_or_else(|err| panic!("failed to load checksum cache: {err}"));
+    let state = web::Data::new(AppState {
+        config: config.clone(),
+        scanner: std::sync::Mutex::new(scanner),
+        encryption_key,
+    });
+
+    let server_config = config.server.clone();
+    HttpServer::new(move || {
+        App::new()
+            .app_data(state.clone())
+            .app_data(admin_directory.clone())
+            .app_data(admin_algorithm.clone())
+            .app_data(admin_key.clone())
+            .route(
+                "/admin/integrity",
+                web::get().to(integrity::admin_check_handler),
+            )
+            .route("/{filename}", web::put().to(routes::upload))
+            .route("/{filename}", web::get().to(routes::download))
+    })
+    .bind((server_config.address.as_str(), server_config.port))?
+    .run()
+    .await
+}
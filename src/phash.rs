@@ -0,0 +1,123 @@
+/// 64-bit perceptual hash (dHash/pHash) of a grayscale-downscaled thumbnail.
+///
+/// Two images that look alike, even if re-encoded or re-compressed to different bytes,
+/// tend to produce hashes with a small Hamming distance.
+pub type PerceptualHash = u64;
+
+/// Returns the Hamming distance between two perceptual hashes.
+fn hamming_distance(a: PerceptualHash, b: PerceptualHash) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// BK-tree node, storing a hash and the index of the [`crate::file::File`] it belongs to.
+struct Node {
+    hash: PerceptualHash,
+    file_index: usize,
+    children: Vec<(u32, Node)>,
+}
+
+impl Node {
+    fn new(hash: PerceptualHash, file_index: usize) -> Self {
+        Self {
+            hash,
+            file_index,
+            children: Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, hash: PerceptualHash, file_index: usize) {
+        let distance = hamming_distance(self.hash, hash);
+        match self
+            .children
+            .iter_mut()
+            .find(|(child_distance, _)| *child_distance == distance)
+        {
+            Some((_, child)) => child.insert(hash, file_index),
+            None => self.children.push((distance, Node::new(hash, file_index))),
+        }
+    }
+
+    fn find_similar(&self, hash: PerceptualHash, max_distance: u32, results: &mut Vec<(usize, u32)>) {
+        let distance = hamming_distance(self.hash, hash);
+        if distance <= max_distance {
+            results.push((self.file_index, distance));
+        }
+        let lower = distance.saturating_sub(max_distance);
+        let upper = distance + max_distance;
+        for (child_distance, child) in &self.children {
+            if *child_distance >= lower && *child_distance <= upper {
+                child.find_similar(hash, max_distance, results);
+            }
+        }
+    }
+}
+
+/// BK-tree index of perceptual hashes, enabling fast Hamming-radius search for
+/// visually-similar images without comparing against every stored upload.
+#[derive(Default)]
+pub struct PerceptualHashIndex {
+    root: Option<Node>,
+}
+
+impl PerceptualHashIndex {
+    /// Creates an empty index.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts a perceptual hash, tagged with the index of its [`crate::file::File`] in
+    /// [`crate::file::Directory::files`].
+    pub fn insert(&mut self, hash: PerceptualHash, file_index: usize) {
+        match &mut self.root {
+            Some(root) => root.insert(hash, file_index),
+            None => self.root = Some(Node::new(hash, file_index)),
+        }
+    }
+
+    /// Returns the `file_index` and Hamming distance of every entry within `max_distance`
+    /// of `hash`, ordered by insertion.
+    pub fn find_similar(&self, hash: PerceptualHash, max_distance: u32) -> Vec<(usize, u32)> {
+        let mut results = Vec::new();
+        if let Some(root) = &self.root {
+            root.find_similar(hash, max_distance, &mut results);
+        }
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hamming_distance() {
+        assert_eq!(0, hamming_distance(0b1010, 0b1010));
+        assert_eq!(1, hamming_distance(0b1010, 0b1000));
+        assert_eq!(4, hamming_distance(0b0000, 0b1111));
+    }
+
+    #[test]
+    fn test_find_similar_within_radius() {
+        let mut index = PerceptualHashIndex::new();
+        index.insert(0b0000_0000, 0);
+        index.insert(0b0000_0001, 1);
+        index.insert(0b0000_0011, 2);
+        index.insert(0b1111_1111, 3);
+
+        let mut matches = index.find_similar(0b0000_0000, 1);
+        matches.sort_by_key(|(file_index, _)| *file_index);
+        assert_eq!(vec![(0, 0), (1, 1)], matches);
+
+        let mut matches = index.find_similar(0b0000_0000, 2);
+        matches.sort_by_key(|(file_index, _)| *file_index);
+        assert_eq!(vec![(0, 0), (1, 1), (2, 2)], matches);
+
+        assert!(index.find_similar(0b1111_1111, 0).contains(&(3, 0)));
+    }
+
+    #[test]
+    fn test_find_similar_on_empty_index() {
+        let index = PerceptualHashIndex::new();
+        assert!(index.find_similar(0, 64).is_empty());
+    }
+}
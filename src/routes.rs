@@ -0,0 +1,133 @@
+use crate::cache::CachedDirectoryScanner;
+use crate::config::Config;
+use crate::crypto::EncryptionKey;
+use crate::file::Directory;
+use actix_web::{error, web, Error as ActixError, HttpResponse};
+use std::fs;
+use std::sync::Mutex;
+
+/// Server-wide state shared across requests: the parsed config, the checksum-cache-backed
+/// directory scanner (reused across requests so repeated scans of the upload directory only
+/// rehash files that changed since the previous one), and the at-rest encryption key, if
+/// encryption is enabled.
+pub struct AppState {
+    /// Parsed server config.
+    pub config: Config,
+    /// Checksum-cache-backed scanner over the upload directory.
+    pub scanner: Mutex<CachedDirectoryScanner>,
+    /// At-rest encryption key, if `[paste.encryption]` is enabled.
+    pub encryption_key: Option<EncryptionKey>,
+}
+
+/// `PUT /{filename}`: stores the request body under `filename` in the upload directory.
+///
+/// An existing file with the same checksum, or a near-duplicate image within the
+/// configured perceptual-hash distance, is reused instead of storing a duplicate copy.
+/// Rejects the upload with `413 Payload Too Large` if storing it would push the directory
+/// over `[paste].max_content_length`. Stored encrypted at rest when `[paste.encryption]`
+/// is enabled.
+pub async fn upload(
+    state: web::Data<AppState>,
+    filename: web::Path<String>,
+    body: web::Bytes,
+) -> Result<HttpResponse, ActixError> {
+    let algorithm = state.config.paste.checksum.algorithm();
+    let upload_path = &state.config.paste.path;
+    let encryption_key = state.encryption_key.as_ref();
+
+    let directory = state
+        .scanner
+        .lock()
+        .unwrap()
+        .scan(upload_path, algorithm, encryption_key)?;
+
+    if let Some(checksum) = crate::util::digest_bytes(algorithm, &body) {
+        if let Some(existing) = directory.get_file(&checksum, algorithm) {
+            return Ok(HttpResponse::Ok().body(format!(
+                "{}\n",
+                existing.path.file_name().and_then(|name| name.to_str()).unwrap_or_default()
+            )));
+        }
+    }
+
+    let phash_config = &state.config.paste.phash;
+    if phash_config.enabled() {
+        if let Some(hash) = crate::util::perceptual_hash_bytes(&body) {
+            let index = directory.perceptual_hash_index();
+            if let Some(existing) = directory
+                .find_similar(&index, hash, phash_config.max_distance())
+                .into_iter()
+                .next()
+            {
+                return Ok(HttpResponse::Ok().body(format!(
+                    "{}\n",
+                    existing.path.file_name().and_then(|name| name.to_str()).unwrap_or_default()
+                )));
+            }
+        }
+    }
+
+    if let Some(max_size) = state.config.paste.max_size()? {
+        let projected = Directory {
+            files: Vec::new(),
+            total_size: directory.total_size + body.len() as u64,
+        };
+        if projected.is_over_size_limit(max_size) {
+            return Err(error::ErrorPayloadTooLarge("upload directory is full"));
+        }
+    }
+
+    let path = upload_path.join(filename.into_inner());
+    match encryption_key {
+        Some(key) => {
+            Directory::store_encrypted(path.clone(), &body, algorithm, key)?;
+        }
+        None => fs::write(&path, &body).map_err(error::ErrorInternalServerError)?,
+    }
+
+    // Refresh the cache so the new file is immediately visible to later uploads/downloads.
+    state
+        .scanner
+        .lock()
+        .unwrap()
+        .scan(upload_path, algorithm, encryption_key)?;
+
+    Ok(HttpResponse::Ok().body(format!(
+        "{}\n",
+        path.file_name().and_then(|name| name.to_str()).unwrap_or_default()
+    )))
+}
+
+/// `GET /{filename}`: serves back a previously uploaded file, decrypting it first if it is
+/// stored encrypted at rest.
+pub async fn download(
+    state: web::Data<AppState>,
+    filename: web::Path<String>,
+) -> Result<HttpResponse, ActixError> {
+    let algorithm = state.config.paste.checksum.algorithm();
+    let upload_path = &state.config.paste.path;
+    let encryption_key = state.encryption_key.as_ref();
+    let directory = state
+        .scanner
+        .lock()
+        .unwrap()
+        .scan(upload_path, algorithm, encryption_key)?;
+
+    let name = filename.into_inner();
+    let file = directory
+        .files
+        .iter()
+        .find(|file| file.path.file_name().and_then(|n| n.to_str()) == Some(name.as_str()))
+        .ok_or_else(|| error::ErrorNotFound("file not found"))?;
+
+    let bytes = match (&file.encrypted_meta, encryption_key) {
+        (Some(_), Some(key)) => Directory::read_decrypted(file, key)?,
+        (Some(_), None) => {
+            return Err(error::ErrorInternalServerError(
+                "file is encrypted at rest but no encryption key is configured",
+            ))
+        }
+        (None, _) => fs::read(&file.path).map_err(error::ErrorInternalServerError)?,
+    };
+    Ok(HttpResponse::Ok().content_type("application/octet-stream").body(bytes))
+}
@@ -0,0 +1,121 @@
+use crate::file::ChecksumAlgorithm;
+use crate::phash::PerceptualHash;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use digest::Digest;
+use image::imageops::FilterType;
+use image::GenericImageView;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::fs::File as OsFile;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+/// Matches the `.<timestamp>.<ext>` suffix rustypaste appends when a name collides.
+pub static TIMESTAMP_EXTENSION_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\.\d+\.[^.]+$").expect("invalid regex"));
+
+/// Size of the `BufReader` used when streaming a file for hashing, so large uploads are
+/// never loaded into memory in one shot.
+const BUFFER_SIZE: usize = 8192;
+
+fn stream_digest<D: Digest>(file: OsFile) -> io::Result<String> {
+    let mut reader = BufReader::with_capacity(BUFFER_SIZE, file);
+    let mut hasher = D::new();
+    let mut buffer = [0u8; BUFFER_SIZE];
+    loop {
+        let bytes_read = reader.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..bytes_read]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Computes the SHA-256 digest of `file`, streaming it through an 8 KiB buffer.
+pub fn sha256_digest(file: OsFile) -> io::Result<String> {
+    stream_digest::<sha2::Sha256>(file)
+}
+
+/// Computes the SHA-512 digest of `file`, streaming it through an 8 KiB buffer.
+pub fn sha512_digest(file: OsFile) -> io::Result<String> {
+    stream_digest::<sha2::Sha512>(file)
+}
+
+/// Computes the MD5 digest of `file`, kept for compatibility with existing checksum stores.
+pub fn md5_digest(file: OsFile) -> io::Result<String> {
+    stream_digest::<md5::Md5>(file)
+}
+
+/// Computes the SHA-1 digest of `file`, kept for compatibility with existing checksum stores.
+pub fn sha1_digest(file: OsFile) -> io::Result<String> {
+    stream_digest::<sha1::Sha1>(file)
+}
+
+/// Computes the BLAKE3 digest of `file`, the fast path for large directories.
+pub fn blake3_digest(file: OsFile) -> io::Result<String> {
+    let mut reader = BufReader::with_capacity(BUFFER_SIZE, file);
+    let mut hasher = blake3::Hasher::new();
+    let mut buffer = [0u8; BUFFER_SIZE];
+    loop {
+        let bytes_read = reader.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..bytes_read]);
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Computes `algorithm`'s digest over in-memory `bytes`, for content that has already been
+/// read into memory (e.g. decrypted plaintext) rather than an open file handle.
+pub fn digest_bytes(algorithm: ChecksumAlgorithm, bytes: &[u8]) -> Option<String> {
+    Some(match algorithm {
+        ChecksumAlgorithm::Md5 => hex::encode(md5::Md5::digest(bytes)),
+        ChecksumAlgorithm::Sha1 => hex::encode(sha1::Sha1::digest(bytes)),
+        ChecksumAlgorithm::Sha256 => hex::encode(sha2::Sha256::digest(bytes)),
+        ChecksumAlgorithm::Sha512 => hex::encode(sha2::Sha512::digest(bytes)),
+        ChecksumAlgorithm::Blake3 => blake3::hash(bytes).to_hex().to_string(),
+    })
+}
+
+/// Decodes a standard-alphabet base64 string, e.g. an encryption master key loaded from
+/// the environment.
+pub fn base64_decode(encoded: &str) -> Result<Vec<u8>, base64::DecodeError> {
+    STANDARD.decode(encoded)
+}
+
+/// Computes a 64-bit dHash perceptual hash from a grayscale, 9x8-downscaled thumbnail of
+/// the image at `path`. Returns `None` if `path` does not decode as an image.
+pub fn perceptual_hash(path: &Path) -> Option<PerceptualHash> {
+    let image = image::open(path).ok()?;
+    Some(dhash(&image))
+}
+
+/// Computes a perceptual hash from in-memory image `bytes`, for content that has already
+/// been read into memory (e.g. decrypted plaintext) rather than a path on disk.
+pub fn perceptual_hash_bytes(bytes: &[u8]) -> Option<PerceptualHash> {
+    let image = image::load_from_memory(bytes).ok()?;
+    Some(dhash(&image))
+}
+
+/// dHash: downscale to 9x8 grayscale, then set each bit based on whether a pixel is
+/// brighter than its left neighbor. Visually similar images downscale to similar
+/// gradients, so re-encoded/re-compressed copies land within a small Hamming distance.
+fn dhash(image: &image::DynamicImage) -> PerceptualHash {
+    let small = image
+        .resize_exact(9, 8, FilterType::Triangle)
+        .grayscale();
+    let mut hash: PerceptualHash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            let left = small.get_pixel(x, y).0[0];
+            let right = small.get_pixel(x + 1, y).0[0];
+            hash <<= 1;
+            if left > right {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}